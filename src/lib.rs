@@ -4,6 +4,8 @@
 #[deny(missing_debug_implementations, missing_docs, warnings)]
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
 
 /// Escapes a string so it will be interpreted as a single word by the UNIX Bourne shell.
 ///
@@ -33,6 +35,98 @@ pub fn escape(input: &str) -> String {
     LINE_FEED.replace_all(output, "'\n'").to_string()
 }
 
+/// A command line interpreter that [`escape_for`] can quote a word for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Shell {
+    /// The UNIX Bourne shell, as understood by [`escape`] and [`split`].
+    Bourne,
+
+    /// Windows `cmd.exe`, and any program using the standard MSVCRT argv decoding rules.
+    WindowsCmd,
+}
+
+/// Escapes a string so it will be interpreted as a single word by `shell`.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::{escape_for, Shell};
+/// # fn main() {
+/// assert_eq!(escape_for("special's.txt", Shell::Bourne), "special\\'s.txt");
+/// assert_eq!(escape_for("has space", Shell::WindowsCmd), "\"has space\"");
+/// # }
+/// ```
+pub fn escape_for(input: &str, shell: Shell) -> String {
+    match shell {
+        Shell::Bourne => escape(input),
+        Shell::WindowsCmd => escape_windows_cmd(input),
+    }
+}
+
+/// Escapes a string so it will be interpreted as a single word by the current platform's native
+/// command line interpreter (`cmd.exe` on Windows, the Bourne shell everywhere else).
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::escape_platform;
+/// # fn main() {
+/// # if !cfg!(windows) {
+/// assert_eq!(escape_platform("special's.txt"), "special\\'s.txt");
+/// # }
+/// # }
+/// ```
+pub fn escape_platform(input: &str) -> String {
+    if cfg!(windows) {
+        escape_for(input, Shell::WindowsCmd)
+    } else {
+        escape_for(input, Shell::Bourne)
+    }
+}
+
+/// Escapes a string per the MSVCRT argv decoding rules used by `cmd.exe` and
+/// `CreateProcess`-based argv builders: empty arguments and arguments containing a space, a tab,
+/// or `"` are wrapped in double quotes, embedded quotes are escaped with a backslash, and a run of
+/// backslashes immediately preceding a quote is itself doubled so it isn't interpreted as
+/// escaping that quote.
+fn escape_windows_cmd(input: &str) -> String {
+    if !input.is_empty() && !input.chars().any(|c| c == ' ' || c == '\t' || c == '"') {
+        return input.to_owned();
+    }
+
+    let mut output = String::from("\"");
+    let mut chars = input.chars().peekable();
+
+    loop {
+        let mut backslashes = 0;
+
+        while chars.peek() == Some(&'\\') {
+            chars.next();
+            backslashes += 1;
+        }
+
+        match chars.next() {
+            Some('"') => {
+                output.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                output.push('"');
+            }
+            Some(c) => {
+                output.extend(std::iter::repeat_n('\\', backslashes));
+                output.push(c);
+            }
+            None => {
+                output.extend(std::iter::repeat_n('\\', backslashes * 2));
+                break;
+            }
+        }
+    }
+
+    output.push('"');
+    output
+}
+
 /// Builds a command line string from a list of arguments.
 ///
 /// The arguments are combined into a single string with each word separated by a space. Each
@@ -95,40 +189,589 @@ pub fn join(args: &[&str]) -> String {
 /// ```
 ///
 pub fn split(input: &str) -> Result<Vec<String>, MismatchedQuotes> {
-    lazy_static! {
-        static ref MAIN_PATTERN: Regex = Regex::new(
-            r#"(?m:\s*(?:([^\s\\'"]+)|'([^']*)'|"((?:[^"\\]|\\.)*)"|(\\.?)|(\S))(\s|\z)?)"#
-        )
-        .unwrap();
-        static ref ESCAPE_PATTERN: Regex = Regex::new(r#"\\(.)"#).unwrap();
-        static ref METACHAR_PATTERN: Regex = Regex::new(r#"\\([$`"\\\n])"#).unwrap();
+    Ok(split_borrowed(input)?
+        .into_iter()
+        .map(Cow::into_owned)
+        .collect())
+}
+
+/// Splits a string the same way [`split`] does, but borrows each word from `input` instead of
+/// allocating, falling back to an owned `String` only for words that actually contain a quote or
+/// a backslash that must be resolved.
+///
+/// Plain whitespace-separated words (the common case) produce zero heap allocations.
+///
+/// # Errors
+///
+/// If the input contains mismatched quotes (a quoted string missing a matching ending quote),
+/// a `MismatchedQuotes` error is returned.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::split_borrowed;
+/// # use std::borrow::Cow;
+/// # fn main() {
+/// let words = split_borrowed("here are \"two words\"").unwrap();
+/// assert_eq!(words, vec!["here", "are", "two words"]);
+/// assert!(matches!(words[0], Cow::Borrowed(_)));
+/// # }
+/// ```
+pub fn split_borrowed(input: &str) -> Result<Vec<Cow<'_, str>>, MismatchedQuotes> {
+    let mut words = Vec::new();
+    let mut cursor = Cursor::new(input);
+
+    loop {
+        while matches!(cursor.peek(), Some(c) if c.is_whitespace()) {
+            cursor.bump();
+        }
+
+        if cursor.peek().is_none() {
+            break;
+        }
+
+        words.push(scan_word(input, &mut cursor)?);
+    }
+
+    Ok(words)
+}
+
+/// A cursor over the byte offsets of `input`'s characters, used by the hand-written scanner
+/// behind [`split_borrowed`].
+struct Cursor<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Cursor { input, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
     }
 
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+}
+
+/// Appends `s` to `value`, staying `Cow::Borrowed` for as long as only a single contiguous slice
+/// has been appended, and forking to an owned buffer the moment a second slice needs joining.
+fn extend<'a>(value: &mut Cow<'a, str>, first: &mut bool, s: &'a str) {
+    if s.is_empty() {
+        return;
+    }
+
+    if *first {
+        *value = Cow::Borrowed(s);
+        *first = false;
+    } else {
+        value.to_mut().push_str(s);
+    }
+}
+
+/// Scans the word starting at `cursor`'s current position (a `Delimiter` state has already been
+/// left behind by the caller), alternating between `Unquoted`, `SingleQuoted`, and `DoubleQuoted`
+/// runs -- plus their backslash states -- until whitespace or the end of input closes the word.
+fn scan_word<'a>(input: &'a str, cursor: &mut Cursor<'a>) -> Result<Cow<'a, str>, MismatchedQuotes> {
+    let mut value: Cow<str> = Cow::Borrowed("");
+    let mut first = true;
+    let mut run_start = cursor.pos;
+
+    loop {
+        match cursor.peek() {
+            None => {
+                extend(&mut value, &mut first, &input[run_start..cursor.pos]);
+                break;
+            }
+            Some(c) if c.is_whitespace() => {
+                extend(&mut value, &mut first, &input[run_start..cursor.pos]);
+                break;
+            }
+            Some('\\') => {
+                extend(&mut value, &mut first, &input[run_start..cursor.pos]);
+                cursor.bump();
+
+                match cursor.peek() {
+                    Some('\n') => {
+                        // An unquoted backslash escapes any character except a newline, so it is
+                        // kept literal here and the newline is left for the next iteration to
+                        // close the word as whitespace.
+                        extend(&mut value, &mut first, "\\");
+                        run_start = cursor.pos;
+                    }
+                    Some(_) => {
+                        let start = cursor.pos;
+                        cursor.bump();
+                        extend(&mut value, &mut first, &input[start..cursor.pos]);
+                        run_start = cursor.pos;
+                    }
+                    None => {
+                        // A lone trailing backslash escapes nothing, so it is kept literally.
+                        extend(&mut value, &mut first, "\\");
+                        break;
+                    }
+                }
+            }
+            Some('\'') => {
+                extend(&mut value, &mut first, &input[run_start..cursor.pos]);
+                cursor.bump();
+
+                let inner_start = cursor.pos;
+                let mut closed = false;
+
+                while let Some(c) = cursor.bump() {
+                    if c == '\'' {
+                        closed = true;
+                        break;
+                    }
+                }
+
+                if !closed {
+                    return Err(MismatchedQuotes);
+                }
+
+                extend(&mut value, &mut first, &input[inner_start..cursor.pos - 1]);
+                run_start = cursor.pos;
+            }
+            Some('"') => {
+                extend(&mut value, &mut first, &input[run_start..cursor.pos]);
+                cursor.bump();
+
+                let mut inner_run_start = cursor.pos;
+                let mut closed = false;
+
+                loop {
+                    match cursor.peek() {
+                        None => break,
+                        Some('"') => {
+                            extend(&mut value, &mut first, &input[inner_run_start..cursor.pos]);
+                            cursor.bump();
+                            closed = true;
+                            break;
+                        }
+                        Some('\\') => {
+                            extend(&mut value, &mut first, &input[inner_run_start..cursor.pos]);
+                            cursor.bump();
+
+                            match cursor.peek() {
+                                Some(next) => {
+                                    let next_start = cursor.pos;
+                                    cursor.bump();
+
+                                    if matches!(next, '$' | '`' | '"' | '\\' | '\n') {
+                                        extend(&mut value, &mut first, &input[next_start..cursor.pos]);
+                                    } else {
+                                        extend(&mut value, &mut first, "\\");
+                                        extend(&mut value, &mut first, &input[next_start..cursor.pos]);
+                                    }
+
+                                    inner_run_start = cursor.pos;
+                                }
+                                None => break,
+                            }
+                        }
+                        Some(_) => {
+                            cursor.bump();
+                        }
+                    }
+                }
+
+                if !closed {
+                    return Err(MismatchedQuotes);
+                }
+
+                run_start = cursor.pos;
+            }
+            Some(_) => {
+                cursor.bump();
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+/// The quoting a word is under at the point [`word_at`] stopped resolving it.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum QuoteContext {
+    /// The cursor is not inside a quoted string.
+    Unquoted,
+
+    /// The cursor is inside a (possibly unterminated) single-quoted string.
+    SingleQuoted,
+
+    /// The cursor is inside a (possibly unterminated) double-quoted string.
+    DoubleQuoted,
+}
+
+/// The result of [`word_at`]: the word under (or immediately preceding) a cursor position.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Completion {
+    /// The byte range of the whole word in the original input. If the word is inside an
+    /// unterminated quote, this runs to the end of the input.
+    pub range: Range<usize>,
+
+    /// The resolved (unescaped) text of the word from its start up to the cursor.
+    pub prefix: String,
+
+    /// The quoting in effect at the cursor.
+    pub context: QuoteContext,
+}
+
+/// Finds the word under (or immediately preceding) `cursor`, the way a line editor needs to in
+/// order to offer completions.
+///
+/// Returns `None` if `cursor` does not fall within any word -- for example, it sits among
+/// whitespace that separates two words. Otherwise, the returned [`Completion`] gives the full
+/// byte range of the word, the resolved text from its start up to `cursor`, and the quoting
+/// [`Completion::context`] is in at that point. A cursor inside an unterminated quote is reported
+/// with that quote's context rather than causing an error, so editors can complete mid-quote
+/// filenames that contain spaces. Because of this, unlike [`split`], this function never actually
+/// returns `Err` -- the `Result` is kept for symmetry with the rest of the crate's entry points.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::{word_at, QuoteContext};
+/// # fn main() {
+/// let completion = word_at("open a\\", 7).unwrap().unwrap();
+/// assert_eq!(completion.range, 5..7);
+/// assert_eq!(completion.prefix, "a\\");
+/// assert_eq!(completion.context, QuoteContext::Unquoted);
+/// # }
+/// ```
+pub fn word_at(input: &str, cursor: usize) -> Result<Option<Completion>, MismatchedQuotes> {
+    let mut scan = Cursor::new(input);
+
+    loop {
+        while matches!(scan.peek(), Some(c) if c.is_whitespace()) {
+            scan.bump();
+        }
+
+        let start = scan.pos;
+
+        if scan.peek().is_none() || cursor < start {
+            return Ok(None);
+        }
+
+        let (end, prefix, context) = scan_word_prefix(input, &mut scan, cursor);
+
+        if cursor <= end {
+            return Ok(Some(Completion {
+                range: start..end,
+                prefix,
+                context,
+            }));
+        }
+    }
+}
+
+/// Appends the resolved text for a source span to `prefix`, stopping the moment `target` (the
+/// completion cursor) falls before the end of that span. `literal`, when given, means the
+/// span can be sliced 1:1 from `input` (an unquoted or quoted literal run); its absence means the
+/// span is an indivisible resolved backslash escape that is only included if `target` is at or
+/// past its end, and excluded entirely otherwise.
+#[allow(clippy::too_many_arguments)]
+fn capture_prefix(
+    prefix: &mut String,
+    done: &mut bool,
+    result_context: &mut Option<QuoteContext>,
+    mode: QuoteContext,
+    target: usize,
+    span: Range<usize>,
+    literal: Option<&str>,
+    resolved: &str,
+) {
+    if *done {
+        return;
+    }
+
+    if target >= span.end {
+        prefix.push_str(resolved);
+    } else if let Some(literal) = literal {
+        prefix.push_str(&literal[..target.max(span.start) - span.start]);
+        *done = true;
+        *result_context = Some(mode);
+    } else {
+        *done = true;
+        *result_context = Some(mode);
+    }
+}
+
+/// Scans the word starting at `cursor`'s current position the same way [`scan_word`] does, but
+/// stops resolving content once `target` is reached, reporting the quoting in effect at that
+/// point. Always advances `cursor` to the word's true end (or the end of input, for an
+/// unterminated quote) so the caller can continue scanning subsequent words.
+fn scan_word_prefix<'a>(
+    input: &'a str,
+    cursor: &mut Cursor<'a>,
+    target: usize,
+) -> (usize, String, QuoteContext) {
+    let mut prefix = String::new();
+    let mut done = false;
+    let mut result_context = None;
+    let mut mode = QuoteContext::Unquoted;
+    let mut run_start = cursor.pos;
+
+    loop {
+        match cursor.peek() {
+            None => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                break;
+            }
+            Some(c) if c.is_whitespace() && mode == QuoteContext::Unquoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                break;
+            }
+            Some('\\') if mode != QuoteContext::SingleQuoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+
+                let backslash_start = cursor.pos;
+                cursor.bump();
+
+                match cursor.peek() {
+                    Some('\n') if mode == QuoteContext::Unquoted => {
+                        // An unquoted backslash escapes any character except a newline, so it is
+                        // kept literal here and the newline is left for the next iteration to
+                        // close the word as whitespace.
+                        capture_prefix(
+                            &mut prefix,
+                            &mut done,
+                            &mut result_context,
+                            mode,
+                            target,
+                            backslash_start..cursor.pos,
+                            None,
+                            "\\",
+                        );
+                        run_start = cursor.pos;
+                    }
+                    Some(next) => {
+                        cursor.bump();
+
+                        let resolved = if mode == QuoteContext::DoubleQuoted
+                            && !matches!(next, '$' | '`' | '"' | '\\' | '\n')
+                        {
+                            ['\\', next].iter().collect()
+                        } else {
+                            next.to_string()
+                        };
+
+                        capture_prefix(
+                            &mut prefix,
+                            &mut done,
+                            &mut result_context,
+                            mode,
+                            target,
+                            backslash_start..cursor.pos,
+                            None,
+                            &resolved,
+                        );
+                        run_start = cursor.pos;
+                    }
+                    None => {
+                        capture_prefix(
+                            &mut prefix,
+                            &mut done,
+                            &mut result_context,
+                            mode,
+                            target,
+                            backslash_start..cursor.pos,
+                            None,
+                            "\\",
+                        );
+                        break;
+                    }
+                }
+            }
+            Some('\'') if mode == QuoteContext::Unquoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                cursor.bump();
+                mode = QuoteContext::SingleQuoted;
+                run_start = cursor.pos;
+            }
+            Some('\'') if mode == QuoteContext::SingleQuoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                cursor.bump();
+                mode = QuoteContext::Unquoted;
+                run_start = cursor.pos;
+            }
+            Some('"') if mode == QuoteContext::Unquoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                cursor.bump();
+                mode = QuoteContext::DoubleQuoted;
+                run_start = cursor.pos;
+            }
+            Some('"') if mode == QuoteContext::DoubleQuoted => {
+                let span = run_start..cursor.pos;
+                let s = &input[span.clone()];
+                capture_prefix(&mut prefix, &mut done, &mut result_context, mode, target, span, Some(s), s);
+                cursor.bump();
+                mode = QuoteContext::Unquoted;
+                run_start = cursor.pos;
+            }
+            Some(_) => {
+                cursor.bump();
+            }
+        }
+    }
+
+    (cursor.pos, prefix, result_context.unwrap_or(mode))
+}
+
+/// Options controlling the behavior of [`split_with_options`].
+///
+/// The default options match the behavior of [`split`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct SplitOptions {
+    /// Whether an unquoted `#` at a word boundary begins a comment that runs to the end of the
+    /// line and is discarded, matching POSIX shell word splitting.
+    pub comments: bool,
+}
+
+/// Splits a string the same way [`split`] does, but allows opting into additional behavior via
+/// `options`.
+///
+/// With [`SplitOptions::comments`] enabled, an unquoted `#` that begins a word (i.e. one preceded
+/// only by whitespace, or at the start of the input) starts a comment that runs to the next
+/// newline and is discarded. A `#` appearing inside a quoted string, in the middle of a word, or
+/// escaped with a backslash is treated as a literal character instead.
+///
+/// # Errors
+///
+/// If the input contains mismatched quotes (a quoted string missing a matching ending quote),
+/// a `MismatchedQuotes` error is returned.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::{split_with_options, SplitOptions};
+/// # fn main() {
+/// let options = SplitOptions { comments: true };
+/// assert_eq!(
+///     split_with_options("echo hi # note", &options).unwrap(),
+///     ["echo", "hi"]
+/// );
+/// # }
+/// ```
+pub fn split_with_options(
+    input: &str,
+    options: &SplitOptions,
+) -> Result<Vec<String>, MismatchedQuotes> {
     let mut words = Vec::new();
-    let mut field = String::new();
+    let mut cursor = Cursor::new(input);
 
-    for capture in MAIN_PATTERN.captures_iter(input) {
-        if let Some(word) = capture.get(1) {
-            field.push_str(word.as_str());
-        } else if let Some(single_quoted_word) = capture.get(2) {
-            field.push_str(single_quoted_word.as_str());
-        } else if let Some(double_quoted_word) = capture.get(3) {
-            field.push_str(&METACHAR_PATTERN.replace_all(double_quoted_word.as_str(), "$1"));
-        } else if let Some(escape) = capture.get(4) {
-            field.push_str(&ESCAPE_PATTERN.replace_all(escape.as_str(), "$1"));
-        } else if capture.get(5).is_some() {
-            return Err(MismatchedQuotes);
+    loop {
+        while matches!(cursor.peek(), Some(c) if c.is_whitespace()) {
+            cursor.bump();
         }
 
-        if capture.get(6).is_some() {
-            words.push(field);
-            field = String::new();
+        if options.comments && cursor.peek() == Some('#') {
+            while !matches!(cursor.peek(), None | Some('\n')) {
+                cursor.bump();
+            }
+
+            cursor.bump();
+            continue;
         }
+
+        if cursor.peek().is_none() {
+            break;
+        }
+
+        words.push(scan_word(input, &mut cursor)?.into_owned());
     }
 
     Ok(words)
 }
 
+/// The result of [`split_with_parts`]: each resolved word together with metadata callers need to
+/// edit the original input, such as a line editor performing tab-completion.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Parts {
+    /// Each resolved word paired with the byte range of `input` that produced it. The range
+    /// covers the untouched original text, including any surrounding quotes and backslashes, with
+    /// only the separating whitespace before and after it excluded.
+    pub words: Vec<(String, Range<usize>)>,
+
+    /// Whether the input ends on unescaped whitespace, or is empty. When true, the last word is
+    /// already closed, so a caller doing completion should start a new word rather than extend
+    /// the previous one.
+    pub ends_with_whitespace: bool,
+}
+
+/// Splits a string the same way [`split`] does, but also returns the original byte range that
+/// produced each word and whether the input ends on trailing whitespace.
+///
+/// This makes it possible to implement correct tab-completion: given the byte range for the word
+/// under the cursor, a caller can delete exactly `input[range]` before substituting a completion
+/// candidate, rather than re-escaping the resolved word and risking the result not matching what
+/// the user actually typed (for example, losing a trailing backslash).
+///
+/// # Errors
+///
+/// If the input contains mismatched quotes (a quoted string missing a matching ending quote),
+/// a `MismatchedQuotes` error is returned.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate shellwords;
+/// # use shellwords::split_with_parts;
+/// # fn main() {
+/// let parts = split_with_parts("here are \"two words\"").unwrap();
+/// assert_eq!(parts.words[2], ("two words".to_string(), 9..20));
+/// assert!(!parts.ends_with_whitespace);
+/// # }
+/// ```
+pub fn split_with_parts(input: &str) -> Result<Parts, MismatchedQuotes> {
+    let mut words = Vec::new();
+    let mut cursor = Cursor::new(input);
+    let ends_with_whitespace;
+
+    loop {
+        let before_whitespace = cursor.pos;
+
+        while matches!(cursor.peek(), Some(c) if c.is_whitespace()) {
+            cursor.bump();
+        }
+
+        if cursor.peek().is_none() {
+            ends_with_whitespace = cursor.pos > before_whitespace || words.is_empty();
+            break;
+        }
+
+        let start = cursor.pos;
+        let word = scan_word(input, &mut cursor)?;
+        words.push((word.into_owned(), start..cursor.pos));
+    }
+
+    Ok(Parts {
+        words,
+        ends_with_whitespace,
+    })
+}
+
 /// An error when splitting a string with mismatched quotes.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct MismatchedQuotes;
@@ -143,7 +786,11 @@ impl std::error::Error for MismatchedQuotes {}
 
 #[cfg(test)]
 mod tests {
-    use super::{escape, join, split, MismatchedQuotes};
+    use super::{
+        escape, escape_for, join, split, split_borrowed, split_with_options, split_with_parts,
+        word_at, MismatchedQuotes, QuoteContext, Shell, SplitOptions,
+    };
+    use std::borrow::Cow;
 
     #[test]
     fn nothing_special() {
@@ -170,6 +817,11 @@ mod tests {
         assert_eq!(split("a b\\ c d").unwrap(), ["a", "b c", "d"]);
     }
 
+    #[test]
+    fn escaped_newline_is_not_escaped() {
+        assert_eq!(split("x\\\ny").unwrap(), ["x\\", "y"]);
+    }
+
     #[test]
     fn bad_double_quotes() {
         assert_eq!(split("a \"b c d e").unwrap_err(), MismatchedQuotes);
@@ -243,4 +895,174 @@ mod tests {
     fn percent_signs() {
         assert_eq!(split("abc '%foo bar%'").unwrap(), ["abc", "%foo bar%"]);
     }
+
+    #[test]
+    fn parts_include_quotes_and_backslashes_in_their_ranges() {
+        let parts = split_with_parts("here are \"two words\" a\\ b").unwrap();
+
+        assert_eq!(
+            parts.words,
+            [
+                ("here".to_string(), 0..4),
+                ("are".to_string(), 5..8),
+                ("two words".to_string(), 9..20),
+                ("a b".to_string(), 21..25),
+            ]
+        );
+        assert!(!parts.ends_with_whitespace);
+    }
+
+    #[test]
+    fn parts_flag_trailing_whitespace() {
+        assert!(split_with_parts("a b ").unwrap().ends_with_whitespace);
+        assert!(!split_with_parts("a b").unwrap().ends_with_whitespace);
+        assert!(split_with_parts("").unwrap().ends_with_whitespace);
+    }
+
+    #[test]
+    fn parts_reject_mismatched_quotes() {
+        assert_eq!(
+            split_with_parts("a \"b c d e").unwrap_err(),
+            MismatchedQuotes
+        );
+    }
+
+    #[test]
+    fn comments_disabled_by_default() {
+        let options = SplitOptions::default();
+        assert_eq!(
+            split_with_options("echo hi # note", &options).unwrap(),
+            ["echo", "hi", "#", "note"]
+        );
+    }
+
+    #[test]
+    fn comments_run_to_the_next_newline() {
+        let options = SplitOptions { comments: true };
+        assert_eq!(
+            split_with_options("echo hi # note\nworld", &options).unwrap(),
+            ["echo", "hi", "world"]
+        );
+    }
+
+    #[test]
+    fn comments_only_start_at_a_word_boundary() {
+        let options = SplitOptions { comments: true };
+        assert_eq!(
+            split_with_options("foo#bar baz", &options).unwrap(),
+            ["foo#bar", "baz"]
+        );
+    }
+
+    #[test]
+    fn comments_do_not_start_inside_quotes_or_when_escaped() {
+        let options = SplitOptions { comments: true };
+        assert_eq!(
+            split_with_options("echo \"hi # not a comment\" hi\\#not", &options).unwrap(),
+            ["echo", "hi # not a comment", "hi#not"]
+        );
+    }
+
+    #[test]
+    fn escape_for_bourne_matches_escape() {
+        assert_eq!(
+            escape_for("special's.txt", Shell::Bourne),
+            escape("special's.txt")
+        );
+    }
+
+    #[test]
+    fn escape_for_windows_cmd_leaves_plain_words_alone() {
+        assert_eq!(escape_for("plain.txt", Shell::WindowsCmd), "plain.txt");
+    }
+
+    #[test]
+    fn escape_for_windows_cmd_quotes_whitespace_and_quotes() {
+        assert_eq!(
+            escape_for("has space", Shell::WindowsCmd),
+            "\"has space\""
+        );
+        assert_eq!(
+            escape_for("quote\"inside", Shell::WindowsCmd),
+            "\"quote\\\"inside\""
+        );
+    }
+
+    #[test]
+    fn escape_for_windows_cmd_doubles_backslashes_before_a_quote() {
+        assert_eq!(
+            escape_for("quote\\\"after backslash", Shell::WindowsCmd),
+            "\"quote\\\\\\\"after backslash\""
+        );
+        assert_eq!(escape_for("trailing\\", Shell::WindowsCmd), "trailing\\");
+    }
+
+    #[test]
+    fn escape_for_windows_cmd_empty_string() {
+        assert_eq!(escape_for("", Shell::WindowsCmd), "\"\"");
+    }
+
+    #[test]
+    fn borrowed_plain_words_do_not_allocate() {
+        for word in split_borrowed("a b c").unwrap() {
+            assert!(matches!(word, Cow::Borrowed(_)));
+        }
+    }
+
+    #[test]
+    fn borrowed_quoted_word_with_no_escapes_does_not_allocate() {
+        let words = split_borrowed("'a b c'").unwrap();
+        assert!(matches!(words[0], Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn borrowed_word_needing_an_escape_falls_back_to_owned() {
+        let words = split_borrowed("a\\ b").unwrap();
+        assert!(matches!(words[0], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn borrowed_matches_split_on_mismatched_quotes() {
+        assert_eq!(
+            split_borrowed("a \"b c d e").unwrap_err(),
+            MismatchedQuotes
+        );
+    }
+
+    #[test]
+    fn word_at_none_in_a_whitespace_gap() {
+        assert_eq!(word_at("foo  bar", 4).unwrap(), None);
+        assert_eq!(word_at("", 0).unwrap(), None);
+    }
+
+    #[test]
+    fn word_at_mid_unquoted_word() {
+        let completion = word_at("foo bar", 2).unwrap().unwrap();
+        assert_eq!(completion.range, 0..3);
+        assert_eq!(completion.prefix, "fo");
+        assert_eq!(completion.context, QuoteContext::Unquoted);
+    }
+
+    #[test]
+    fn word_at_keeps_a_trailing_backslash_in_the_prefix() {
+        let completion = word_at("open a\\", 7).unwrap().unwrap();
+        assert_eq!(completion.range, 5..7);
+        assert_eq!(completion.prefix, "a\\");
+        assert_eq!(completion.context, QuoteContext::Unquoted);
+    }
+
+    #[test]
+    fn word_at_inside_an_unterminated_quote() {
+        let completion = word_at("echo \"hello world", 13).unwrap().unwrap();
+        assert_eq!(completion.range, 5..17);
+        assert_eq!(completion.prefix, "hello w");
+        assert_eq!(completion.context, QuoteContext::DoubleQuoted);
+    }
+
+    #[test]
+    fn word_at_excludes_an_escape_the_cursor_lands_inside() {
+        let completion = word_at("echo \"a\\tb\" more", 8).unwrap().unwrap();
+        assert_eq!(completion.prefix, "a");
+        assert_eq!(completion.context, QuoteContext::DoubleQuoted);
+    }
 }